@@ -2,18 +2,18 @@
 
 use std::path::PathBuf;
 
-#[tauri::command]
-fn ensure_backup_dir() -> Result<String, String> {
+// Resolves the Application Support directory for this app, preferring the
+// system-wide location and falling back to the per-user one, creating it if
+// necessary. Shared by `ensure_backup_dir` and the backend pidfile location.
+fn app_support_dir() -> Result<PathBuf, String> {
   // Prefer system-wide Application Support (/Library/Application Support/...)
   let system_base: PathBuf = PathBuf::from("/")
     .join("Library")
     .join("Application Support")
     .join("iMessageWrapped");
 
-  if let Ok(_) = std::fs::create_dir_all(&system_base) {
-    let backups = system_base.join("backups");
-    std::fs::create_dir_all(&backups).map_err(|e| e.to_string())?;
-    return Ok(backups.to_string_lossy().to_string());
+  if std::fs::create_dir_all(&system_base).is_ok() {
+    return Ok(system_base);
   }
 
   // Fallback to per-user Application Support
@@ -24,7 +24,20 @@ fn ensure_backup_dir() -> Result<String, String> {
     .join("iMessageWrapped");
 
   std::fs::create_dir_all(&user_base).map_err(|e| e.to_string())?;
-  let backups = user_base.join("backups");
+  Ok(user_base)
+}
+
+#[tauri::command]
+fn ensure_backup_dir() -> Result<String, String> {
+  // A configured `backups_dir` always wins over the default location.
+  if let Ok(config) = load_config() {
+    if let Some(dir) = config.backups_dir {
+      std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+      return Ok(dir);
+    }
+  }
+
+  let backups = app_support_dir()?.join("backups");
   std::fs::create_dir_all(&backups).map_err(|e| e.to_string())?;
   Ok(backups.to_string_lossy().to_string())
 }
@@ -55,83 +68,665 @@ fn normalize_path(path: String) -> Result<String, String> {
 
 use serde_json::Value;
 
-#[tauri::command]
-fn run_backend(payload: Value) -> Result<String, String> {
-  // Accept either `exports_dir` (snake_case) or `exportsDir` (camelCase)
-  let exports_dir = payload
+// Accept either `exports_dir` (snake_case) or `exportsDir` (camelCase)
+fn extract_exports_dir(payload: &Value) -> Option<String> {
+  payload
     .get("exports_dir")
     .and_then(|v: &Value| v.as_str())
     .map(|s| s.to_string())
-    .or_else(|| payload.get("exportsDir").and_then(|v: &Value| v.as_str()).map(|s| s.to_string()));
+    .or_else(|| payload.get("exportsDir").and_then(|v: &Value| v.as_str()).map(|s| s.to_string()))
+}
 
-  if let Some(ed) = exports_dir {
-    run_backend_internal(&ed)
-  } else {
-    Err("Missing required parameter `exports_dir` or `exportsDir`".to_string())
+#[tauri::command]
+fn run_backend(state: tauri::State<AppState>, payload: Value) -> Result<String, String> {
+  match extract_exports_dir(&payload) {
+    Some(ed) => run_backend_internal(&state, &ed),
+    None => Err("Missing required parameter `exports_dir` or `exportsDir`".to_string()),
   }
 }
 
-// Core backend runner used by both the Tauri command and the local HTTP server
-fn run_backend_internal(exports_dir: &str) -> Result<String, String> {
-  use std::process::Command;
-  // Try packaged binary first (relative to project root src-tauri/binaries)
-  let cwd = std::env::current_dir().map_err(|e| e.to_string())?;
-  let mut bin_path = cwd.join("src-tauri").join("binaries").join("MessagesWrapped");
-  if !bin_path.exists() {
-    // try without src-tauri (in case current_dir is src-tauri)
-    bin_path = cwd.join("binaries").join("MessagesWrapped");
+// Streaming variant: runs the backend as a child process and emits each line of
+// its stdout through `channel` as it arrives, instead of buffering the whole run.
+// The frontend gets a live progress log and a final terminal message carrying
+// the exit status, rather than a single blob returned after the process exits.
+#[tauri::command]
+fn run_backend_streamed(app_handle: tauri::AppHandle, channel: tauri::ipc::Channel<String>, payload: Value) -> Result<(), String> {
+  let exports_dir = match extract_exports_dir(&payload) {
+    Some(ed) => ed,
+    None => return Err("Missing required parameter `exports_dir` or `exportsDir`".to_string()),
+  };
+
+  std::thread::spawn(move || {
+    let state = app_handle.state::<AppState>();
+    if let Err(e) = run_backend_streamed_internal(&state, &exports_dir, &channel) {
+      let _ = channel.send(format!("__TERMINAL__ error: {}", e));
+    }
+  });
+
+  Ok(())
+}
+
+// Stops the currently-tracked backend run, if any, by killing the child process
+// and reaping it so its slot in `state` is freed for the next run.
+#[tauri::command]
+fn cancel_backend(state: tauri::State<AppState>) -> Result<(), String> {
+  let mut guard = state.running_child.lock().unwrap();
+  match guard.take() {
+    Some(mut running) => {
+      running.child.kill().map_err(|e| e.to_string())?;
+      let _ = running.child.wait();
+      clear_pidfile();
+      Ok(())
+    }
+    None => Err("No backend run is currently active".to_string()),
   }
+}
 
-  let args = [format!("--exports-dir={}", exports_dir), String::from("--max-workers=4")];
+// The protocol version this build of the UI speaks to the backend. Bumped
+// whenever the shape of `BackendResult` changes in a way the backend needs to
+// know about.
+const PROTOCOL_VERSION: u32 = 1;
 
-  if bin_path.exists() {
-    match Command::new(bin_path).args(&args).output() {
-      Ok(out) => {
-        let mut combined = String::new();
-        combined.push_str(&String::from_utf8_lossy(&out.stdout));
-        combined.push_str(&String::from_utf8_lossy(&out.stderr));
-        if out.status.success() {
-          Ok(combined)
-        } else {
-          Err(combined)
-        }
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
+struct ContactCount {
+  contact: String,
+  message_count: u64,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
+struct BackendSummary {
+  total_messages: u64,
+  total_contacts: u64,
+  contacts: Vec<ContactCount>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
+struct BackendResult {
+  protocol_version: u32,
+  summary: BackendSummary,
+  #[serde(default)]
+  errors: Vec<String>,
+}
+
+// Typed error surfaced to the frontend for the structured JSON run, so a
+// protocol-version mismatch between the bundled binary and the UI can be
+// distinguished from a spawn failure or malformed output.
+#[derive(Debug, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum BackendJsonError {
+  ProtocolMismatch { expected: u32, actual: u32 },
+  BadRequest(String),
+  Spawn(String),
+  InvalidOutput(String),
+}
+
+impl std::fmt::Display for BackendJsonError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      BackendJsonError::ProtocolMismatch { expected, actual } => {
+        write!(f, "backend speaks protocol v{}, UI expects v{}", actual, expected)
       }
-      Err(e) => Err(format!("Failed to execute binary: {}", e)),
+      BackendJsonError::BadRequest(e) | BackendJsonError::Spawn(e) | BackendJsonError::InvalidOutput(e) => write!(f, "{}", e),
     }
+  }
+}
+
+// Structured JSON result mode: runs the backend with `--format=json` and the
+// negotiated protocol version, parses its final stdout line as a
+// `BackendResult`, and returns it as a `serde_json::Value` instead of an
+// opaque text blob.
+#[tauri::command]
+fn run_backend_json(state: tauri::State<AppState>, payload: Value) -> Result<Value, BackendJsonError> {
+  let exports_dir = extract_exports_dir(&payload)
+    .ok_or_else(|| BackendJsonError::BadRequest("Missing required parameter `exports_dir` or `exportsDir`".to_string()))?;
+  run_backend_json_internal(&state, &exports_dir)
+}
+
+fn run_backend_json_internal(state: &AppState, exports_dir: &str) -> Result<Value, BackendJsonError> {
+  validate_exports_dir(state, exports_dir).map_err(BackendJsonError::BadRequest)?;
+
+  let config = load_config().map_err(BackendJsonError::Spawn)?;
+  let extra_args = [String::from("--format=json"), format!("--protocol-version={}", PROTOCOL_VERSION)];
+  let cmd = build_backend_command(&config, exports_dir, &extra_args).map_err(BackendJsonError::Spawn)?;
+  let (stdout, stderr) = spawn_tracked(state, cmd).map_err(BackendJsonError::Spawn)?;
+
+  let (out_buf, err_buf) = read_child_output(stdout, stderr);
+  reap_tracked(state).map_err(BackendJsonError::Spawn)?;
+
+  let last_line = out_buf
+    .lines()
+    .rev()
+    .find(|line| !line.trim().is_empty())
+    .ok_or_else(|| BackendJsonError::InvalidOutput(format!("Backend produced no JSON output. stderr: {}", err_buf)))?;
+
+  let result: BackendResult =
+    serde_json::from_str(last_line).map_err(|e| BackendJsonError::InvalidOutput(format!("Failed to parse backend JSON output: {}", e)))?;
+
+  if result.protocol_version != PROTOCOL_VERSION {
+    return Err(BackendJsonError::ProtocolMismatch { expected: PROTOCOL_VERSION, actual: result.protocol_version });
+  }
+
+  serde_json::to_value(&result).map_err(|e| BackendJsonError::InvalidOutput(e.to_string()))
+}
+
+// Core streaming runner: spawns the backend with piped stdout and forwards each
+// line through `channel` as it arrives, followed by a final `__TERMINAL__` line
+// carrying the exit status once the child exits. On error it sends no terminal
+// line itself; `run_backend_streamed` sends one carrying the returned error.
+fn run_backend_streamed_internal(state: &AppState, exports_dir: &str, channel: &tauri::ipc::Channel<String>) -> Result<(), String> {
+  use std::io::{BufRead, BufReader};
+
+  validate_exports_dir(state, exports_dir)?;
+  let config = load_config()?;
+  let cmd = build_backend_command(&config, exports_dir, &[])?;
+  let (stdout, stderr) = spawn_tracked(state, cmd)?;
+
+  // Drain stderr on its own thread, forwarding it through the same channel.
+  // Otherwise an unread stderr pipe fills up and the child blocks on its next
+  // write to it, stalling stdout (and this whole run) right along with it.
+  let stderr_channel = channel.clone();
+  let stderr_thread = std::thread::spawn(move || {
+    for line in BufReader::new(stderr).lines().flatten() {
+      let _ = stderr_channel.send(format!("STDERR: {}", line));
+    }
+  });
+
+  let reader = BufReader::new(stdout);
+  for line in reader.lines() {
+    match line {
+      Ok(l) => {
+        let _ = channel.send(l);
+      }
+      Err(e) => {
+        let _ = reap_tracked(state);
+        let _ = stderr_thread.join();
+        return Err(e.to_string());
+      }
+    }
+  }
+  let _ = stderr_thread.join();
+
+  let status = reap_tracked(state)?;
+  let code = status.map(|s| s.code().unwrap_or(-1)).unwrap_or(-1);
+  let _ = channel.send(format!("__TERMINAL__ exit_code={}", code));
+  Ok(())
+}
+
+// Resolves the packaged binary path and the fallback python script path,
+// shared by both the buffered and streamed backend runners. A configured
+// `backend_binary_override` always wins over the search.
+fn resolve_backend_paths(config: &AppConfig) -> Result<(PathBuf, PathBuf), String> {
+  let cwd = std::env::current_dir().map_err(|e| e.to_string())?;
+
+  let bin_path = if let Some(override_path) = &config.backend_binary_override {
+    PathBuf::from(override_path)
   } else {
-    // Fallback to running the python script from the Backend folder
-    // Try ../Backend/MessagesWrapped.py and Backend/MessagesWrapped.py
-    let mut script_path = cwd.join("../Backend/MessagesWrapped.py");
-    if !script_path.exists() {
-      script_path = cwd.join("Backend").join("MessagesWrapped.py");
+    let mut bin_path = cwd.join("src-tauri").join("binaries").join("MessagesWrapped");
+    if !bin_path.exists() {
+      // try without src-tauri (in case current_dir is src-tauri)
+      bin_path = cwd.join("binaries").join("MessagesWrapped");
     }
+    bin_path
+  };
+
+  let mut script_path = cwd.join("../Backend/MessagesWrapped.py");
+  if !script_path.exists() {
+    script_path = cwd.join("Backend").join("MessagesWrapped.py");
+  }
+
+  Ok((bin_path, script_path))
+}
+
+fn default_max_workers() -> u32 {
+  4
+}
+
+// Persistent, user-overridable settings for running the backend: worker
+// count, backup directory, and binary/interpreter locations. Loaded from and
+// saved to a JSON file in the per-user Application Support directory.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+struct AppConfig {
+  #[serde(default = "default_max_workers")]
+  max_workers: u32,
+  #[serde(default)]
+  backups_dir: Option<String>,
+  #[serde(default)]
+  python_executable: Option<String>,
+  #[serde(default)]
+  backend_binary_override: Option<String>,
+}
+
+impl Default for AppConfig {
+  fn default() -> Self {
+    AppConfig { max_workers: default_max_workers(), backups_dir: None, python_executable: None, backend_binary_override: None }
+  }
+}
+
+fn config_path() -> Result<PathBuf, String> {
+  let home = std::env::var("HOME").map_err(|e| e.to_string())?;
+  let user_base: PathBuf = PathBuf::from(home)
+    .join("Library")
+    .join("Application Support")
+    .join("iMessageWrapped");
+  std::fs::create_dir_all(&user_base).map_err(|e| e.to_string())?;
+  Ok(user_base.join("config.json"))
+}
+
+// Loads the persistent config, falling back to defaults when the file is
+// absent or a field is missing.
+#[tauri::command]
+fn load_config() -> Result<AppConfig, String> {
+  let path = config_path()?;
+  match std::fs::read_to_string(&path) {
+    Ok(contents) => serde_json::from_str(&contents).map_err(|e| e.to_string()),
+    Err(_) => Ok(AppConfig::default()),
+  }
+}
+
+#[tauri::command]
+fn save_config(config: AppConfig) -> Result<(), String> {
+  let path = config_path()?;
+  let contents = serde_json::to_string_pretty(&config).map_err(|e| e.to_string())?;
+  std::fs::write(&path, contents).map_err(|e| e.to_string())
+}
+
+// A single allowed root directory for `FsScope`. When `recursive` is true, any
+// path nested under `root` is in scope; when false, only direct children are.
+struct ScopeRoot {
+  root: PathBuf,
+  recursive: bool,
+}
+
+// Allow/deny layer gating which directories `run_backend` may point the
+// backend subprocess at, mirroring Tauri's FsScope: denied glob patterns take
+// precedence over allowed roots.
+struct FsScope {
+  allowed: Vec<ScopeRoot>,
+  denied_patterns: Vec<String>,
+}
+
+impl FsScope {
+  // Defaults to the user's Messages export locations (`~/Library/Messages` and
+  // `~/Documents`) and the backup dir from `ensure_backup_dir`, so a fresh
+  // launch can run the backend without any extra configuration.
+  fn with_defaults() -> Self {
+    let mut allowed = Vec::new();
+
+    if let Ok(home) = std::env::var("HOME") {
+      let home = PathBuf::from(home);
+      allowed.push(ScopeRoot { root: home.join("Library").join("Messages"), recursive: true });
+      allowed.push(ScopeRoot { root: home.join("Documents"), recursive: true });
+    }
+    if let Ok(backups_dir) = ensure_backup_dir() {
+      allowed.push(ScopeRoot { root: PathBuf::from(backups_dir), recursive: true });
+    }
+
+    FsScope {
+      allowed,
+      denied_patterns: vec!["**/.ssh/**".to_string(), "**/Library/Keychains/**".to_string()],
+    }
+  }
+
+  fn allow_root(&mut self, root: PathBuf) {
+    self.allowed.push(ScopeRoot { root, recursive: true });
+  }
+
+  fn allowed_roots(&self) -> Vec<String> {
+    self.allowed.iter().map(|entry| entry.root.to_string_lossy().to_string()).collect()
+  }
+
+  // Canonicalizes `path` and checks it against the denied patterns, then the
+  // allowed roots. Denied patterns always win, even over an allowed root.
+  fn is_allowed(&self, path: &std::path::Path) -> bool {
+    let canonical = match path.canonicalize() {
+      Ok(p) => p,
+      Err(_) => return false,
+    };
+    let text = canonical.to_string_lossy();
+
+    if self.denied_patterns.iter().any(|pattern| glob_match(pattern, &text)) {
+      return false;
+    }
+
+    self.allowed.iter().any(|entry| {
+      let root = match entry.root.canonicalize() {
+        Ok(r) => r,
+        Err(_) => return false,
+      };
+      if entry.recursive {
+        canonical.starts_with(&root)
+      } else {
+        canonical.parent() == Some(root.as_path())
+      }
+    })
+  }
+}
+
+// Matches `text` (a `/`-separated path) against a glob `pattern` supporting
+// `*` (any run of characters within one path segment) and `**` (any run of
+// segments, including zero).
+fn glob_match(pattern: &str, text: &str) -> bool {
+  let pattern_segments: Vec<&str> = pattern.split('/').collect();
+  let text_segments: Vec<&str> = text.split('/').collect();
+  glob_match_segments(&pattern_segments, &text_segments)
+}
+
+fn glob_match_segments(pattern: &[&str], text: &[&str]) -> bool {
+  match pattern.first() {
+    None => text.is_empty(),
+    Some(&"**") => {
+      if pattern.len() == 1 {
+        return true;
+      }
+      (0..=text.len()).any(|i| glob_match_segments(&pattern[1..], &text[i..]))
+    }
+    Some(segment) => {
+      !text.is_empty() && glob_match_segment(segment, text[0]) && glob_match_segments(&pattern[1..], &text[1..])
+    }
+  }
+}
+
+fn glob_match_segment(pattern: &str, text: &str) -> bool {
+  match pattern.find('*') {
+    None => pattern == text,
+    Some(idx) => {
+      let prefix = &pattern[..idx];
+      let suffix = &pattern[idx + 1..];
+      text.len() >= prefix.len() + suffix.len() && text.starts_with(prefix) && text.ends_with(suffix)
+    }
+  }
+}
+
+// Rejects `exports_dir` before any `Command` is constructed if it falls
+// outside the configured `FsScope`.
+fn validate_exports_dir(state: &AppState, exports_dir: &str) -> Result<(), String> {
+  let scope = state.fs_scope.lock().unwrap();
+  if scope.is_allowed(std::path::Path::new(exports_dir)) {
+    Ok(())
+  } else {
+    Err(format!("exports_dir `{}` is outside the allowed export directories", exports_dir))
+  }
+}
+
+// Adds a new allowed root directory to the export-path scope at runtime.
+#[tauri::command]
+fn allow_export_root(state: tauri::State<AppState>, root: String) -> Result<(), String> {
+  state.fs_scope.lock().unwrap().allow_root(PathBuf::from(root));
+  Ok(())
+}
+
+// Lists the currently allowed root directories, for display in the frontend.
+#[tauri::command]
+fn list_allowed_export_roots(state: tauri::State<AppState>) -> Result<Vec<String>, String> {
+  Ok(state.fs_scope.lock().unwrap().allowed_roots())
+}
+
+// Builds the `Command` for either the packaged binary or the fallback python
+// script, without spawning it, so callers can attach stdio and hand it to
+// `spawn_tracked`. `extra_args` is appended after the standard flags, e.g. for
+// `--format=json --protocol-version=N`. Reads `max_workers` and
+// `python_executable` from `config` instead of hard-coded defaults.
+fn build_backend_command(config: &AppConfig, exports_dir: &str, extra_args: &[String]) -> Result<std::process::Command, String> {
+  use std::process::Command;
+
+  let (bin_path, script_path) = resolve_backend_paths(config)?;
+  let mut args = vec![format!("--exports-dir={}", exports_dir), format!("--max-workers={}", config.max_workers)];
+  args.extend(extra_args.iter().cloned());
+
+  if bin_path.exists() {
+    let mut cmd = Command::new(bin_path);
+    cmd.args(&args);
+    Ok(cmd)
+  } else {
     if !script_path.exists() {
       return Err(format!("No backend binary or script found. Checked {}", script_path.display()));
     }
+    let python = config.python_executable.clone().unwrap_or_else(|| "python3".to_string());
+    let mut cmd = Command::new(python);
+    cmd.arg(script_path).args(&args);
+    Ok(cmd)
+  }
+}
 
-    match Command::new("python3").arg(script_path).args(&args).output() {
-      Ok(out) => {
-        let mut combined = String::new();
-        combined.push_str(&String::from_utf8_lossy(&out.stdout));
-        combined.push_str(&String::from_utf8_lossy(&out.stderr));
-        if out.status.success() {
-          Ok(combined)
-        } else {
-          Err(combined)
-        }
+// Core backend runner used by the Tauri command, the local HTTP server and the
+// `imwrapped://` scheme handler
+fn run_backend_internal(state: &AppState, exports_dir: &str) -> Result<String, String> {
+  validate_exports_dir(state, exports_dir)?;
+  let config = load_config()?;
+  let cmd = build_backend_command(&config, exports_dir, &[])?;
+  let (stdout, stderr) = spawn_tracked(state, cmd)?;
+
+  let (mut combined, err_buf) = read_child_output(stdout, stderr);
+  combined.push_str(&err_buf);
+
+  match reap_tracked(state)? {
+    Some(status) if status.success() => Ok(combined),
+    Some(_) => Err(combined),
+    None => Err("Backend run was cancelled".to_string()),
+  }
+}
+
+// The currently-running backend child, tracked so it can be cancelled and so
+// concurrent runs can be refused instead of silently spawning duplicates.
+struct RunningProcess {
+  child: std::process::Child,
+  #[allow(dead_code)]
+  pid: u32,
+}
+
+// Per-launch state. `run_token` gates access to the `/run` capability (both the
+// legacy loopback server and the `imwrapped://` scheme) so that no local
+// process or web page can trigger a backend run without knowing a secret that
+// only this launch of the app generated. `running_child` tracks the active
+// backend process, if any, so it can be cancelled and so concurrent runs are
+// refused rather than silently spawning duplicates.
+struct AppState {
+  run_token: String,
+  running_child: std::sync::Mutex<Option<RunningProcess>>,
+  fs_scope: std::sync::Mutex<FsScope>,
+}
+
+// Spawns `cmd` with piped stdio, refusing if a backend run is already active,
+// and registers the child with `state` so `cancel_backend` can kill it later.
+// Returns the child's stdout/stderr handles, taken before the child moves into
+// `state` for tracking.
+fn spawn_tracked(state: &AppState, mut cmd: std::process::Command) -> Result<(std::process::ChildStdout, std::process::ChildStderr), String> {
+  use std::process::Stdio;
+
+  let mut guard = state.running_child.lock().unwrap();
+  if let Some(running) = guard.as_mut() {
+    if matches!(running.child.try_wait(), Ok(None)) {
+      return Err("A backend run is already in progress".to_string());
+    }
+  }
+
+  let mut child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().map_err(|e| format!("Failed to spawn backend: {}", e))?;
+  let pid = child.id();
+  let stdout = child.stdout.take().ok_or_else(|| "Failed to capture backend stdout".to_string())?;
+  let stderr = child.stderr.take().ok_or_else(|| "Failed to capture backend stderr".to_string())?;
+
+  write_pidfile(pid);
+  *guard = Some(RunningProcess { child, pid });
+  Ok((stdout, stderr))
+}
+
+// Reads `stdout` and `stderr` to completion concurrently, on a thread each.
+// `Command::output()` does this internally precisely to avoid a deadlock: if
+// the child fills one pipe's buffer while nobody is draining it, the child
+// blocks on that write and stops producing the other stream too. Since
+// `spawn_tracked` hands the streams out separately, callers that need both
+// buffered must read them concurrently rather than one after the other.
+fn read_child_output(mut stdout: std::process::ChildStdout, mut stderr: std::process::ChildStderr) -> (String, String) {
+  use std::io::Read;
+
+  let stderr_thread = std::thread::spawn(move || {
+    let mut buf = String::new();
+    let _ = stderr.read_to_string(&mut buf);
+    buf
+  });
+
+  let mut out_buf = String::new();
+  let _ = stdout.read_to_string(&mut out_buf);
+
+  let err_buf = stderr_thread.join().unwrap_or_default();
+  (out_buf, err_buf)
+}
+
+// Waits for the currently-tracked child to exit and clears it from `state`.
+// Returns `Ok(None)` if there was nothing to reap (e.g. `cancel_backend`
+// already took and reaped it).
+fn reap_tracked(state: &AppState) -> Result<Option<std::process::ExitStatus>, String> {
+  let mut guard = state.running_child.lock().unwrap();
+  match guard.take() {
+    Some(mut running) => {
+      let status = running.child.wait().map(Some).map_err(|e| e.to_string());
+      clear_pidfile();
+      status
+    }
+    None => Ok(None),
+  }
+}
+
+// Path of the pidfile recording the PID of the backend process this app most
+// recently spawned, so a later launch can tell which running process (if any)
+// it actually owns.
+fn pidfile_path() -> Result<PathBuf, String> {
+  Ok(app_support_dir()?.join("backend.pid"))
+}
+
+fn write_pidfile(pid: u32) {
+  if let Ok(path) = pidfile_path() {
+    let _ = std::fs::write(path, pid.to_string());
+  }
+}
+
+fn clear_pidfile() {
+  if let Ok(path) = pidfile_path() {
+    let _ = std::fs::remove_file(path);
+  }
+}
+
+// Kills the backend process left running from a previous crash of this app,
+// if any. Only ever considers the single PID this app itself recorded in its
+// pidfile the last time it spawned a backend, and only kills it once that
+// process is confirmed still named `MessagesWrapped` with no living parent —
+// i.e. actually orphaned, not a legitimate run still owned by another running
+// instance of this app.
+fn reap_orphaned_backends() {
+  use sysinfo::{Pid, PidExt, ProcessExt, System, SystemExt};
+
+  let path = match pidfile_path() {
+    Ok(p) => p,
+    Err(_) => return,
+  };
+
+  let recorded_pid = std::fs::read_to_string(&path).ok().and_then(|s| s.trim().parse::<usize>().ok());
+
+  if let Some(recorded_pid) = recorded_pid {
+    let mut sys = System::new_all();
+    sys.refresh_processes();
+
+    if let Some(process) = sys.process(Pid::from(recorded_pid)) {
+      let is_ours = process.name() == "MessagesWrapped";
+      // An orphan is reparented to pid 1 (init/launchd), which never counts as
+      // a living owner.
+      let parent_alive = process
+        .parent()
+        .map(|parent_pid| parent_pid.as_u32() != 1 && sys.process(parent_pid).is_some())
+        .unwrap_or(false);
+      if is_ours && !parent_alive {
+        process.kill();
       }
-      Err(e) => Err(format!("Failed to spawn python3: {}", e)),
     }
   }
+
+  let _ = std::fs::remove_file(&path);
 }
 
-// Spawn a tiny local HTTP server on 127.0.0.1:39213 to accept /run?exports_dir=...
-fn spawn_local_runner() {
+// Generates a random 32-byte token, hex-encoded, unique to this launch of the app.
+fn generate_run_token() -> String {
+  use std::io::Read;
+  let mut bytes = [0u8; 32];
+  if std::fs::File::open("/dev/urandom").and_then(|mut f| f.read_exact(&mut bytes)).is_err() {
+    // Fall back to a real CSPRNG if /dev/urandom is unavailable; this only
+    // happens in unusual sandboxed environments, but the token still has to
+    // be genuinely unguessable.
+    use rand::RngCore;
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+  }
+  bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Compares `expected` (the per-launch `run_token`) against `actual` (a token
+// supplied by a caller) in constant time, so a hostile local process can't use
+// response timing to learn how much of the secret's prefix it guessed right.
+fn constant_time_eq(expected: &str, actual: &str) -> bool {
+  let expected = expected.as_bytes();
+  let actual = actual.as_bytes();
+  if expected.len() != actual.len() {
+    return false;
+  }
+  let mut diff = 0u8;
+  for (a, b) in expected.iter().zip(actual.iter()) {
+    diff |= a ^ b;
+  }
+  diff == 0
+}
+
+// Parses a `key=value&key=value` query string into decoded pairs, shared by
+// the loopback server and the `imwrapped://` scheme handler.
+fn parse_query_params(qs: &str) -> std::collections::HashMap<String, String> {
+  let mut params = std::collections::HashMap::new();
+  for pair in qs.split('&') {
+    if pair.is_empty() {
+      continue;
+    }
+    let mut it = pair.splitn(2, '=');
+    let key = it.next().unwrap_or("");
+    let val = it.next().unwrap_or("");
+    params.insert(simple_percent_decode(key), simple_percent_decode(val));
+  }
+  params
+}
+
+// Preferred entry point for running the backend: a registered `imwrapped://`
+// URI-scheme protocol handler that resolves off-thread and inherits Tauri's
+// origin checks, instead of a raw socket any local process could reach.
+// Handles requests like `imwrapped://run?exports_dir=...&token=...`.
+fn register_run_protocol(builder: tauri::Builder<tauri::Wry>) -> tauri::Builder<tauri::Wry> {
+  builder.register_asynchronous_uri_scheme_protocol("imwrapped", |app, request, responder| {
+    let app_handle = app.clone();
+    let query = request.uri().query().unwrap_or("").to_string();
+    std::thread::spawn(move || {
+      let params = parse_query_params(&query);
+      let state = app_handle.state::<AppState>();
+
+      let token_ok = params.get("token").map(|t| constant_time_eq(&state.run_token, t)).unwrap_or(false);
+      if !token_ok {
+        let _ = responder.respond(tauri::http::Response::builder().status(403).body(Vec::new()).unwrap());
+        return;
+      }
+
+      let exports_dir = params.get("exports_dir").cloned().unwrap_or_default();
+      let (status, body) = match run_backend_internal(&state, &exports_dir) {
+        Ok(out) => (200, out),
+        Err(e) => (500, e),
+      };
+      let _ = responder.respond(tauri::http::Response::builder().status(status).body(body.into_bytes()).unwrap());
+    });
+  })
+}
+
+// Legacy loopback HTTP server on 127.0.0.1:39213, kept for compatibility with
+// callers that can't speak the `imwrapped://` scheme. `/run` requests must
+// carry the same per-launch `token` as the scheme handler or they get a 403;
+// any other path gets a 404.
+fn spawn_local_runner(app_handle: tauri::AppHandle) {
   use std::io::{Read, Write};
   use std::net::TcpListener;
   use std::thread;
 
-  thread::spawn(|| {
+  thread::spawn(move || {
     let listener = match TcpListener::bind(("127.0.0.1", 39213)) {
       Ok(l) => l,
       Err(_) => return,
@@ -147,22 +742,20 @@ fn spawn_local_runner() {
           if parts.len() >= 2 {
             let path = parts[1];
             if path.starts_with("/run") {
-              // parse query string
-              let exports_dir = if let Some(qi) = path.find('?') {
-                let qs = &path[qi+1..];
-                // find exports_dir param
-                let mut val = "".to_string();
-                for p in qs.split('&') {
-                  if p.starts_with("exports_dir=") {
-                    val = p[13..].to_string();
-                    break;
-                  }
-                }
-                // percent-decode
-                simple_percent_decode(&val)
-              } else { String::new() };
-
-              let response = match run_backend_internal(&exports_dir) {
+              let params = if let Some(qi) = path.find('?') { parse_query_params(&path[qi + 1..]) } else { std::collections::HashMap::new() };
+
+              let state = app_handle.state::<AppState>();
+              let token_ok = params.get("token").map(|t| constant_time_eq(&state.run_token, t)).unwrap_or(false);
+
+              if !token_ok {
+                let body = "Forbidden";
+                let resp = format!("HTTP/1.1 403 Forbidden\r\nContent-Length: {}\r\n\r\n{}", body.len(), body);
+                let _ = s.write_all(resp.as_bytes());
+                continue;
+              }
+
+              let exports_dir = params.get("exports_dir").cloned().unwrap_or_default();
+              let response = match run_backend_internal(&state, &exports_dir) {
                 Ok(out) => format!("OK\n{}", out),
                 Err(e) => format!("ERROR\n{}", e),
               };
@@ -182,7 +775,7 @@ fn spawn_local_runner() {
   });
 }
 
-// simple percent-decode for query param values
+// simple percent-decode for query param keys/values
 fn simple_percent_decode(input: &str) -> String {
   let mut out = String::with_capacity(input.len());
   let mut chars = input.chars();
@@ -208,11 +801,36 @@ fn simple_percent_decode(input: &str) -> String {
 }
 
 fn main() {
-  // start local HTTP runner (used as a fallback to run backend without relying on Tauri invoke/allowlist)
-  spawn_local_runner();
+  // Clean up any backend process left behind by a previous crash before we
+  // start tracking runs of our own.
+  reap_orphaned_backends();
+
+  let builder = tauri::Builder::default()
+    .manage(AppState {
+      run_token: generate_run_token(),
+      running_child: std::sync::Mutex::new(None),
+      fs_scope: std::sync::Mutex::new(FsScope::with_defaults()),
+    })
+    .setup(|app| {
+      // Keep the loopback server running for compatibility, now gated by the
+      // same per-launch token as the `imwrapped://` scheme handler.
+      spawn_local_runner(app.handle().clone());
+      Ok(())
+    });
 
-  tauri::Builder::default()
-    .invoke_handler(tauri::generate_handler![ensure_backup_dir, normalize_path, run_backend])
+  register_run_protocol(builder)
+    .invoke_handler(tauri::generate_handler![
+      ensure_backup_dir,
+      normalize_path,
+      run_backend,
+      run_backend_streamed,
+      run_backend_json,
+      cancel_backend,
+      allow_export_root,
+      list_allowed_export_roots,
+      load_config,
+      save_config
+    ])
     .run(tauri::generate_context!())
     .expect("error while running tauri application");
 }